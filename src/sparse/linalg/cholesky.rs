@@ -1,11 +1,12 @@
 /// Cholesky factorization
 
 use std::ops::{Deref};
+use std::collections::{BTreeSet, HashMap};
 
-use num::traits::Num;
+use num::traits::{Num, Float};
+use num::complex::Complex;
 
 use sparse::csmat::{CsMat, CompressedStorage};
-use sparse::symmetric::{is_symmetric};
 use sparse::permutation::Permutation;
 
 pub enum SymmetryCheck {
@@ -13,6 +14,222 @@ pub enum SymmetryCheck {
     DontCheckSymmetry
 }
 
+/// Scalar usable in a (Hermitian) LDLᴴ factorization.
+///
+/// It provides the two hooks the factorization needs on top of the usual
+/// ring operations: the complex conjugation used in the triangular updates
+/// and the real part, which is where the magnitudes and the diagonal live.
+/// For real scalars the conjugation is a no-op and `Real` is the type
+/// itself, so `f32`/`f64` behave exactly as before and LDLt stays a special
+/// case of LDLᴴ.
+pub trait LdlScalar: Num + Copy + PartialEq {
+    /// The real field the pivots and magnitudes live in.
+    type Real: Num + Copy + PartialOrd;
+    /// Complex conjugate; the identity on real scalars.
+    fn conj(self) -> Self;
+    /// Real part of the scalar.
+    fn real_part(self) -> Self::Real;
+    /// Embed a real value back into the scalar type (zero imaginary part).
+    fn from_real(re: Self::Real) -> Self;
+}
+
+impl LdlScalar for f64 {
+    type Real = f64;
+    fn conj(self) -> f64 { self }
+    fn real_part(self) -> f64 { self }
+    fn from_real(re: f64) -> f64 { re }
+}
+
+impl LdlScalar for f32 {
+    type Real = f32;
+    fn conj(self) -> f32 { self }
+    fn real_part(self) -> f32 { self }
+    fn from_real(re: f32) -> f32 { re }
+}
+
+impl<T> LdlScalar for Complex<T>
+where T: Float + Num {
+    type Real = T;
+    fn conj(self) -> Complex<T> { Complex::new(self.re, -self.im) }
+    fn real_part(self) -> T { self.re }
+    fn from_real(re: T) -> Complex<T> { Complex::new(re, T::zero()) }
+}
+
+/// Dynamic regularization applied to the pivots during `ldl_numeric`.
+///
+/// Without regularization `ldl_numeric` panics as soon as a pivot vanishes
+/// and silently assumes positive definiteness, which makes it unusable on
+/// the indefinite or nearly-singular systems that show up in optimization
+/// (KKT systems). With a regularization in hand, a pivot whose sign
+/// disagrees with the expected one in `signs`, or whose magnitude falls
+/// below `epsilon`, is perturbed to `sign · max(epsilon, delta, |pivot|)`
+/// instead of aborting — so a flagged pivot always ends up at or above the
+/// threshold — and the number of such perturbations is reported back to the
+/// caller so it can decide whether to trust the solve.
+pub struct LdltRegularization<'a, N: LdlScalar> {
+    /// Expected sign of each diagonal entry of `D` (`+1`, `-1`, or `0` to
+    /// leave the sign unconstrained), or `None` to constrain nothing.
+    ///
+    /// The slice is indexed by elimination order, i.e. in the same permuted
+    /// order the factorization walks the columns: when a fill-reducing `perm`
+    /// is used, entry `k` is the expected sign of the pivot eliminated at step
+    /// `k`, not of original row `k`. It must have exactly `n` entries.
+    pub signs: Option<&'a [i8]>,
+    /// Threshold below which a pivot magnitude is considered too small.
+    pub epsilon: N::Real,
+    /// Lower bound on the magnitude of a perturbed pivot (together with
+    /// `epsilon` and the pivot's own magnitude).
+    pub delta: N::Real,
+}
+
+impl<'a, N> LdltRegularization<'a, N>
+where N: LdlScalar {
+    /// A regularization that leaves every pivot untouched, reproducing the
+    /// historical "panic on singular pivot" behavior.
+    pub fn none() -> LdltRegularization<'a, N> {
+        LdltRegularization {
+            signs: None,
+            epsilon: N::Real::zero(),
+            delta: N::Real::zero(),
+        }
+    }
+}
+
+/// Check that `mat` equals its conjugate transpose (is Hermitian).
+///
+/// For real scalars the conjugation is a no-op and this reduces to the usual
+/// symmetry test.
+fn is_hermitian<N, IStorage, DStorage>(
+    mat: &CsMat<N, IStorage, DStorage>) -> bool
+where
+N: LdlScalar,
+IStorage: Deref<Target=[usize]>,
+DStorage: Deref<Target=[N]> {
+    let mut entries = HashMap::new();
+    for (outer_ind, vec) in mat.outer_iterator() {
+        for (inner_ind, val) in vec.iter() {
+            entries.insert((inner_ind, outer_ind), val);
+        }
+    }
+    for (&(i, j), &val) in entries.iter() {
+        match entries.get(&(j, i)) {
+            Some(&transposed) => if transposed != val.conj() {
+                return false;
+            },
+            None => return false,
+        }
+    }
+    true
+}
+
+/// The elimination tree of a symmetric matrix, with its nodes postordered.
+///
+/// The parent array is what the factorization itself consumes: it drives the
+/// row-pattern assembly of `ldl_symbolic` and `ldl_numeric`. The postorder is
+/// computed alongside it and exposed so callers can reuse the tree for the
+/// column-count and supernode analyses that need a postordering, without
+/// recomputing it themselves.
+pub struct EliminationTree {
+    parent: Vec<isize>,
+    postorder: Vec<usize>,
+}
+
+impl EliminationTree {
+    /// Parent of each node (`-1` for a root).
+    pub fn parents(&self) -> &[isize] {
+        &self.parent
+    }
+
+    /// The nodes of the tree in postorder.
+    pub fn postorder(&self) -> &[usize] {
+        &self.postorder
+    }
+
+    /// The number of nodes in the tree.
+    pub fn len(&self) -> usize {
+        self.parent.len()
+    }
+
+    /// Whether the tree has no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.parent.is_empty()
+    }
+}
+
+/// Derive a postordering of an elimination tree by a non-recursive DFS of
+/// the children lists.
+fn postorder_tree(parent: &[isize]) -> Vec<usize> {
+    let n = parent.len();
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut roots = Vec::new();
+    for i in 0..n {
+        if parent[i] < 0 {
+            roots.push(i);
+        } else {
+            children[parent[i] as usize].push(i);
+        }
+    }
+
+    let mut post = Vec::with_capacity(n);
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    for &root in roots.iter() {
+        stack.push((root, 0));
+        while let Some(&(node, next_child)) = stack.last() {
+            if next_child < children[node].len() {
+                stack.last_mut().unwrap().1 += 1;
+                stack.push((children[node][next_child], 0));
+            } else {
+                post.push(node);
+                stack.pop();
+            }
+        }
+    }
+    post
+}
+
+/// Compute the elimination tree of `mat` under the ordering `perm`.
+///
+/// The tree is built with the standard disjoint-set-with-ancestor-compression
+/// pass over the upper triangle: for each column `j`, and each row `i < j`,
+/// the ancestor pointers are walked from `i` while compressing them towards
+/// `j`, and the parent of the reached root is set to `j`. A postordering of
+/// the resulting tree is computed as well.
+pub fn etree<N, IStorage, DStorage, PStorage>(
+    mat: &CsMat<N, IStorage, DStorage>,
+    perm: &Permutation<PStorage>) -> EliminationTree
+where
+N: Clone + Copy + PartialEq,
+IStorage: Deref<Target=[usize]>,
+DStorage: Deref<Target=[N]>,
+PStorage: Deref<Target=[usize]> {
+    let n = mat.rows();
+    let mut parent = vec![-1isize; n];
+    let mut ancestor = vec![-1isize; n];
+
+    for (_, (outer_ind, vec)) in mat.outer_iterator_papt(&perm.borrowed())
+                                    .enumerate() {
+        let j = outer_ind;
+        for (inner_ind, _) in vec.iter() {
+            let mut i = inner_ind;
+            while i < j {
+                let inext = ancestor[i];
+                ancestor[i] = j as isize; // compress toward j
+                if inext < 0 {
+                    parent[i] = j as isize;
+                    break;
+                }
+                i = inext as usize;
+            }
+        }
+    }
+
+    let postorder = postorder_tree(&parent);
+    EliminationTree {
+        parent: parent,
+        postorder: postorder,
+    }
+}
+
 /// Perform a symbolic LDLt decomposition of a symmetric sparse matrix
 pub fn ldl_symbolic<N, IStorage, DStorage, PStorage>(
     mat: &CsMat<N, IStorage, DStorage>,
@@ -23,24 +240,30 @@ pub fn ldl_symbolic<N, IStorage, DStorage, PStorage>(
     flag_workspace: &mut [usize],
     check_symmetry: SymmetryCheck)
 where
-N: Clone + Copy + PartialEq,
+N: LdlScalar,
 IStorage: Deref<Target=[usize]>,
 DStorage: Deref<Target=[N]>,
 PStorage: Deref<Target=[usize]> {
 
     match check_symmetry {
         SymmetryCheck::DontCheckSymmetry => (),
-        SymmetryCheck::CheckSymmetry => if ! is_symmetric(mat) {
+        SymmetryCheck::CheckSymmetry => if ! is_hermitian(mat) {
             panic!("Matrix is not symmetric")
         }
     }
 
     let n = mat.rows();
 
+    // the elimination tree is now a first-class computation; the pattern
+    // assembly below consumes its parent array
+    let tree = etree(mat, perm);
+    for (dst, &src) in parents.iter_mut().zip(tree.parents().iter()) {
+        *dst = src;
+    }
+
     for (k, (outer_ind, vec)) in mat.outer_iterator_papt(&perm.borrowed()).enumerate() {
 
         flag_workspace[k] = k; // this node is visited
-        parents[k] = -1;
         l_nz[k] = 0;
 
         for (inner_ind, _) in vec.iter() {
@@ -50,15 +273,12 @@ PStorage: Deref<Target=[usize]> {
             // weird as it would introduce a dissimetry between the permuted
             // and non permuted cases. Needs test however
             if i < outer_ind {
-                // get back to the root of the etree
-                // TODO: maybe this calls for a more adequate parent structure?
+                // climb the etree up to the current column, counting the
+                // row's contribution to each ancestor's column
                 while flag_workspace[i] != outer_ind {
-                    if parents[i] == -1 {
-                        parents[i] = outer_ind as isize; // TODO check overflow
-                    }
                     l_nz[i] = l_nz[i] + 1;
                     flag_workspace[i] = outer_ind;
-                    i = parents[i] as usize; // TODO check negative
+                    i = parents[i] as usize;
                 }
             }
         }
@@ -84,14 +304,22 @@ pub fn ldl_numeric<N, IStorage, DStorage, PStorage>(
     diag: &mut [N],
     y_workspace: &mut [N],
     pattern_workspace: &mut [usize],
-    flag_workspace: &mut [usize])
+    flag_workspace: &mut [usize],
+    regularization: &LdltRegularization<N>) -> usize
 where
-N: Clone + Copy + PartialEq + Num + PartialOrd,
+N: LdlScalar,
 IStorage: Deref<Target=[usize]>,
 DStorage: Deref<Target=[N]>,
 PStorage: Deref<Target=[usize]> {
 
     let n = mat.rows();
+    let mut regularized_count = 0;
+
+    // `signs` is consumed in elimination order, one entry per pivot; catch a
+    // short slice here rather than indexing out of bounds mid-factorization
+    if let Some(s) = regularization.signs {
+        debug_assert_eq!(s.len(), n);
+    }
 
     for (k, (outer_ind, vec))
     in mat.outer_iterator_papt(&perm.borrowed()).enumerate() {
@@ -105,7 +333,11 @@ PStorage: Deref<Target=[usize]> {
         let mut top = n;
 
         for (inner_ind, val) in vec.iter().filter(|&(i,_)| i <= k) {
-            y_workspace[inner_ind] = y_workspace[inner_ind] + val;
+            // the permuted column reads the upper triangle entry A[i, k];
+            // for the Hermitian path we need A[k, i] = conj(A[i, k]) so that
+            // the stored factor reconstructs A rather than its conjugate
+            // (a no-op for real scalars)
+            y_workspace[inner_ind] = y_workspace[inner_ind] + val.conj();
             let mut i = inner_ind;
             let mut len = 0;
             while flag_workspace[i] != outer_ind {
@@ -137,18 +369,66 @@ PStorage: Deref<Target=[usize]> {
                 // value in l_indices that will be read on the next iteration
                 // TODO: can some design change make this fact more obvious?
                 let y_index = l_indices[p];
-                y_workspace[y_index] = y_workspace[y_index] - l_data[p] * yi;
+                // LDLᴴ: the update uses the conjugate of the stored L entry,
+                // which is a no-op for real scalars.
+                y_workspace[y_index] =
+                    y_workspace[y_index] - l_data[p].conj() * yi;
             }
             let l_ki = yi / diag[i];
-            diag[k] = diag[k] - l_ki * yi;
+            diag[k] = diag[k] - l_ki * yi.conj();
             l_indices[p2] = k;
             l_data[p2] = l_ki;
             l_nz[i] += 1;
         }
-        if diag[k] == N::zero() {
+        // the diagonal of a Hermitian factorization is real by construction;
+        // drop any rounding imaginary part (identity for real scalars)
+        diag[k] = N::from_real(diag[k].real_part());
+
+        // Dynamic regularization: if the pivot's sign disagrees with the
+        // expected one or its magnitude is too small, perturb it instead of
+        // assuming positive definiteness and panicking. The comparison lives
+        // in the real field the pivots belong to.
+        let zero = N::Real::zero();
+        let dk = diag[k].real_part();
+        let abs = if dk < zero { zero - dk } else { dk };
+        let actual_sign = if dk > zero {
+            1i8
+        } else if dk < zero {
+            -1i8
+        } else {
+            0i8
+        };
+        let expected_sign = regularization.signs.map(|s| s[k]);
+        let sign_bad = match expected_sign {
+            Some(s) if s != 0 => s != actual_sign,
+            _ => false,
+        };
+        if sign_bad || abs < regularization.epsilon {
+            let sign = match expected_sign {
+                Some(s) if s != 0 => s,
+                _ => if actual_sign == 0 { 1 } else { actual_sign },
+            };
+            // push the magnitude up to at least the threshold, so a pivot
+            // flagged as too small can never be clamped back to itself and
+            // left below `epsilon`, regardless of how `delta` compares to it
+            let mut floor = abs;
+            if regularization.epsilon > floor {
+                floor = regularization.epsilon;
+            }
+            if regularization.delta > floor {
+                floor = regularization.delta;
+            }
+            let perturbed_re = if sign < 0 { zero - floor } else { floor };
+            let perturbed = N::from_real(perturbed_re);
+            if perturbed != diag[k] {
+                regularized_count += 1;
+            }
+            diag[k] = perturbed;
+        } else if dk == zero {
             panic!("Matrix is singular");
         }
     }
+    regularized_count
 }
 
 pub fn ldl_lsolve<N>(
@@ -175,17 +455,19 @@ pub fn ldl_ltsolve<N>(
     l_data: &[N],
     x: &mut [N])
 where
-N: Clone + Copy + Num {
+N: LdlScalar {
     // the ltsolve is a very specific iteration on the matrix, we're iterating
     // the outer dimension in reverse but the inner dimension in the usual way
     // It might make sense to abstract it later if it turns out to be
     // a common pattern, but we're better of doing it by hand here for now
+    // This back-substitution is against Lᴴ, so the stored entries are
+    // conjugated (a no-op for real scalars).
     for (outer_ind, inner_window) in l_colptr.windows(2).enumerate().rev() {
         let start = inner_window[0];
         let end = inner_window[1];
         for (&inner_ind, &val)
                 in l_indices[start..end].iter().zip(l_data[start..end].iter()) {
-            x[outer_ind] = x[outer_ind] - val * x[inner_ind];
+            x[outer_ind] = x[outer_ind] - val.conj() * x[inner_ind];
         }
     }
 }
@@ -201,12 +483,353 @@ N: Clone + Copy + Num {
     }
 }
 
+/// Compute an Approximate Minimum Degree fill-reducing ordering for the
+/// symmetric pattern of `mat`.
+///
+/// The ordering is built on the symmetrized pattern of `A + Aᵀ` using the
+/// quotient-graph formulation: eliminated variables become *elements*, each
+/// remaining variable keeps the list of the variables and elements it is
+/// adjacent to, and we repeatedly pick the variable of minimum approximate
+/// external degree, turn it into a new element absorbing the elements it
+/// subsumes, then recompute approximate degrees only for the affected
+/// variables. Variables that end up with identical adjacency are merged into
+/// a *supervariable* and eliminated together, which together with the
+/// approximate (rather than exact) degree update is what keeps the ordering
+/// close to linear in practice.
+///
+/// The resulting permutation can be handed to `ldl_symbolic` (through
+/// `LdltSymbolic::new_perm`) to cut down on fill-in.
+pub fn amd_order<N, IStorage, DStorage>(
+    mat: &CsMat<N, IStorage, DStorage>) -> Permutation<Vec<usize>>
+where
+N: Clone + Copy + PartialEq,
+IStorage: Deref<Target=[usize]>,
+DStorage: Deref<Target=[N]> {
+    let n = mat.rows();
+    assert_eq!(n, mat.cols(), "AMD requires a square pattern");
+
+    // variable-variable adjacency of A + Aᵀ, diagonal excluded
+    let mut vars: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); n];
+    for (j, col) in mat.outer_iterator() {
+        for (i, _) in col.iter() {
+            if i != j {
+                vars[i].insert(j);
+                vars[j].insert(i);
+            }
+        }
+    }
+
+    // variable-element adjacency, and the variable reach of each element
+    let mut elems: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); n];
+    let mut elem_vars: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); n];
+    let mut elem_alive = vec![false; n];
+
+    // supervariable bookkeeping: the original variables each node represents
+    let mut members: Vec<Vec<usize>> = (0..n).map(|i| vec![i]).collect();
+    let mut alive = vec![true; n];
+    let mut degree: Vec<usize> = (0..n).map(|i| vars[i].len()).collect();
+
+    let mut order = Vec::with_capacity(n);
+
+    while order.len() < n {
+        // 1. pick the alive variable of minimum approximate external degree
+        let pivot = (0..n).filter(|&i| alive[i])
+                          .min_by_key(|&i| degree[i])
+                          .expect("an alive variable must remain");
+
+        // 2. the new element's variable reach is the union of the pivot's
+        //    adjacent variables and the reaches of its adjacent elements,
+        //    absorbing those elements in the process
+        let mut reach: BTreeSet<usize> = BTreeSet::new();
+        for &v in &vars[pivot] {
+            if alive[v] && v != pivot {
+                reach.insert(v);
+            }
+        }
+        for &e in &elems[pivot] {
+            for &v in &elem_vars[e] {
+                if alive[v] && v != pivot {
+                    reach.insert(v);
+                }
+            }
+            elem_alive[e] = false; // subsumed by the pivot
+        }
+
+        // eliminate the pivot (and all members of its supervariable)
+        for &m in &members[pivot] {
+            order.push(m);
+        }
+        alive[pivot] = false;
+        elem_vars[pivot] = reach.clone();
+        elem_alive[pivot] = true;
+
+        // 3. fold the pivot element into every variable of the reach and drop
+        //    the now-redundant direct variable edges between them
+        for &i in &reach {
+            elems[i] = elems[i].iter().cloned()
+                               .filter(|&e| elem_alive[e]).collect();
+            elems[i].insert(pivot);
+            for &j in &reach {
+                vars[i].remove(&j);
+            }
+            vars[i].remove(&pivot);
+        }
+
+        // 3'. recompute the approximate external degree of affected variables
+        for &i in &reach {
+            let mut approx = vars[i].len();
+            for &e in &elems[i] {
+                approx += elem_vars[e].len().saturating_sub(1);
+            }
+            degree[i] = approx;
+        }
+
+        // 4. merge supervariables of the reach sharing the same adjacency
+        let reach_vec: Vec<usize> = reach.iter().cloned().collect();
+        for a_pos in 0..reach_vec.len() {
+            let a = reach_vec[a_pos];
+            if !alive[a] {
+                continue;
+            }
+            for b_pos in (a_pos + 1)..reach_vec.len() {
+                let b = reach_vec[b_pos];
+                if !alive[b] {
+                    continue;
+                }
+                if vars[a] == vars[b] && elems[a] == elems[b] {
+                    let b_members = members[b].clone();
+                    members[a].extend(b_members);
+                    alive[b] = false;
+                    // b is now represented by a; drop it from its neighbors'
+                    // adjacency so the approximate external degrees stop
+                    // counting an eliminated supervariable
+                    let b_neighbors: Vec<usize> =
+                        vars[b].iter().cloned().collect();
+                    for v in b_neighbors {
+                        vars[v].remove(&b);
+                    }
+                }
+            }
+        }
+    }
+
+    Permutation::new(order)
+}
+
+/// Result of the symbolic LDLt phase: the elimination tree and the nonzero
+/// pattern of the factor `L`, kept together with the fill-reducing
+/// permutation and the workspaces the numeric phase reuses.
+///
+/// This owns everything `ldl_symbolic` writes into caller-provided slices,
+/// so that a factorization can be obtained with a single call to `factor`
+/// rather than by threading a dozen buffers by hand.
+pub struct LdltSymbolic {
+    perm: Permutation<Vec<usize>>,
+    l_colptr: Vec<usize>,
+    parents: Vec<isize>,
+    l_nz: Vec<usize>,
+    flag_workspace: Vec<usize>,
+}
+
+impl LdltSymbolic {
+    /// Run the symbolic analysis of `mat` with the identity ordering.
+    pub fn new<N, IStorage, DStorage>(
+        mat: &CsMat<N, IStorage, DStorage>) -> LdltSymbolic
+    where
+    N: LdlScalar,
+    IStorage: Deref<Target=[usize]>,
+    DStorage: Deref<Target=[N]> {
+        let perm: Permutation<Vec<usize>> = Permutation::identity();
+        LdltSymbolic::new_perm(mat, perm)
+    }
+
+    /// Run the symbolic analysis of `mat`, applying the fill-reducing
+    /// permutation `perm` before the elimination.
+    pub fn new_perm<N, IStorage, DStorage>(
+        mat: &CsMat<N, IStorage, DStorage>,
+        perm: Permutation<Vec<usize>>) -> LdltSymbolic
+    where
+    N: LdlScalar,
+    IStorage: Deref<Target=[usize]>,
+    DStorage: Deref<Target=[N]> {
+        let n = mat.rows();
+        assert_eq!(n, mat.cols(), "LDLt requires a square matrix");
+        let mut l_colptr = vec![0; n + 1];
+        let mut parents = vec![-1; n];
+        let mut l_nz = vec![0; n];
+        let mut flag_workspace = vec![0; n];
+        ldl_symbolic(mat, &perm, &mut l_colptr, &mut parents, &mut l_nz,
+                     &mut flag_workspace, SymmetryCheck::CheckSymmetry);
+        LdltSymbolic {
+            perm: perm,
+            l_colptr: l_colptr,
+            parents: parents,
+            l_nz: l_nz,
+            flag_workspace: flag_workspace,
+        }
+    }
+
+    /// The size of the system that was analyzed.
+    pub fn problem_size(&self) -> usize {
+        self.parents.len()
+    }
+
+    /// The number of nonzeros in the factor `L`.
+    pub fn nnz(&self) -> usize {
+        let n = self.problem_size();
+        self.l_colptr[n]
+    }
+
+    /// Compute the numeric decomposition of `mat`, which must share the
+    /// pattern analyzed by this symbolic phase.
+    pub fn factor<N, IStorage, DStorage>(
+        self,
+        mat: &CsMat<N, IStorage, DStorage>) -> LdltNumeric<N>
+    where
+    N: LdlScalar,
+    IStorage: Deref<Target=[usize]>,
+    DStorage: Deref<Target=[N]> {
+        let n = self.problem_size();
+        let nnz = self.nnz();
+        let mut ldlt = LdltNumeric {
+            symbolic: self,
+            l_indices: vec![0; nnz],
+            l_data: vec![N::zero(); nnz],
+            diag: vec![N::zero(); n],
+            y_workspace: vec![N::zero(); n],
+            pattern_workspace: vec![0; n],
+            regularized_count: 0,
+        };
+        ldlt.refactor(mat);
+        ldlt
+    }
+}
+
+/// A numeric LDLt factorization owning the factor `L`, the diagonal `D` and
+/// the workspaces it needs, produced by `LdltSymbolic::factor`.
+pub struct LdltNumeric<N> {
+    symbolic: LdltSymbolic,
+    l_indices: Vec<usize>,
+    l_data: Vec<N>,
+    diag: Vec<N>,
+    y_workspace: Vec<N>,
+    pattern_workspace: Vec<usize>,
+    regularized_count: usize,
+}
+
+impl<N> LdltNumeric<N>
+where N: LdlScalar {
+
+    /// Recompute the factorization for a matrix sharing the symbolic
+    /// structure, reusing every workspace. Useful when only the values of
+    /// the matrix changed between two solves.
+    pub fn refactor<IStorage, DStorage>(
+        &mut self,
+        mat: &CsMat<N, IStorage, DStorage>)
+    where
+    IStorage: Deref<Target=[usize]>,
+    DStorage: Deref<Target=[N]> {
+        self.refactor_with(mat, &LdltRegularization::none());
+    }
+
+    /// Like `refactor`, but applying the given dynamic regularization to the
+    /// pivots. Returns the number of pivots that were perturbed.
+    pub fn refactor_with<IStorage, DStorage>(
+        &mut self,
+        mat: &CsMat<N, IStorage, DStorage>,
+        regularization: &LdltRegularization<N>) -> usize
+    where
+    IStorage: Deref<Target=[usize]>,
+    DStorage: Deref<Target=[N]> {
+        self.regularized_count = ldl_numeric(
+            mat, &self.symbolic.l_colptr, &self.symbolic.parents,
+            &self.symbolic.perm, &mut self.symbolic.l_nz,
+            &mut self.l_indices, &mut self.l_data, &mut self.diag,
+            &mut self.y_workspace, &mut self.pattern_workspace,
+            &mut self.symbolic.flag_workspace, regularization);
+        self.regularized_count
+    }
+
+    /// The number of pivots perturbed by dynamic regularization during the
+    /// last factorization.
+    pub fn regularized_pivots(&self) -> usize {
+        self.regularized_count
+    }
+
+    /// Solve the system `A x = b` using the stored factors, chaining the
+    /// forward, diagonal and backward sweeps.
+    pub fn solve(&self, b: &[N]) -> Vec<N> {
+        let mut x = b.to_vec();
+        ldl_lsolve(&self.symbolic.l_colptr, &self.l_indices, &self.l_data,
+                   &mut x);
+        ldl_dsolve(&self.diag, &mut x);
+        ldl_ltsolve(&self.symbolic.l_colptr, &self.l_indices, &self.l_data,
+                    &mut x);
+        x
+    }
+
+    /// Solve the system for several right-hand-sides at once.
+    ///
+    /// `rhs` is a dense column-major buffer of shape `n × nrhs`, overwritten
+    /// in place with the solutions. The `L` structure is traversed exactly
+    /// once per sweep and the update is applied to all `nrhs` columns per
+    /// nonzero, so the traversal cost is amortized over the whole block.
+    pub fn solve_multiple(&self, rhs: &mut [N], nrhs: usize) {
+        let n = self.problem_size();
+        assert_eq!(rhs.len(), n * nrhs,
+                   "rhs must be an n x nrhs column-major buffer");
+
+        // forward solve L X = B, building the CSC view of L only once
+        let l = CsMat::from_slices(
+            CompressedStorage::CSC, n, n, &self.symbolic.l_colptr,
+            &self.l_indices, &self.l_data).unwrap();
+        for (col_ind, vec) in l.outer_iterator() {
+            for (row_ind, value) in vec.iter() {
+                for c in 0..nrhs {
+                    rhs[c * n + row_ind] =
+                        rhs[c * n + row_ind] - value * rhs[c * n + col_ind];
+                }
+            }
+        }
+
+        // diagonal solve D Y = X
+        for r in 0..n {
+            let d = self.diag[r];
+            for c in 0..nrhs {
+                rhs[c * n + r] = rhs[c * n + r] / d;
+            }
+        }
+
+        // backward solve Lᴴ X = Y, iterating the columns of L in reverse
+        for (outer_ind, inner_window)
+                in self.symbolic.l_colptr.windows(2).enumerate().rev() {
+            let start = inner_window[0];
+            let end = inner_window[1];
+            for (&inner_ind, &val)
+                    in self.l_indices[start..end].iter()
+                           .zip(self.l_data[start..end].iter()) {
+                for c in 0..nrhs {
+                    rhs[c * n + outer_ind] =
+                        rhs[c * n + outer_ind] - val.conj() * rhs[c * n + inner_ind];
+                }
+            }
+        }
+    }
+
+    /// The size of the factored system.
+    pub fn problem_size(&self) -> usize {
+        self.symbolic.problem_size()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use sparse::csmat::CsMat;
     use sparse::csmat::CompressedStorage::{CSC};
     use sparse::permutation::Permutation;
-    use super::{SymmetryCheck};
+    use num::complex::Complex;
+    use super::{SymmetryCheck, LdltRegularization, LdltSymbolic, amd_order,
+                etree};
 
     fn test_mat1() -> CsMat<f64, Vec<usize>, Vec<f64>> {
         let indptr = vec![0, 2, 5, 6, 7, 13, 14, 17, 20, 24, 28];
@@ -298,7 +921,7 @@ mod test {
         super::ldl_numeric(&mat, &l_colptr, &parents, &perm, &mut l_nz,
                            &mut l_indices, &mut l_data, &mut diag,
                            &mut y_workspace, &mut pattern_workspace,
-                           &mut flag_workspace);
+                           &mut flag_workspace, &LdltRegularization::none());
 
         let (expected_lp, expected_li, expected_lx, expected_d) = expected_factors1();
 
@@ -347,7 +970,7 @@ mod test {
         super::ldl_numeric(&mat, &l_colptr, &parents, &perm, &mut l_nz,
                            &mut l_indices, &mut l_data, &mut diag,
                            &mut y_workspace, &mut pattern_workspace,
-                           &mut flag_workspace);
+                           &mut flag_workspace, &LdltRegularization::none());
 
         let b = test_vec1();
         let mut x = b.clone();
@@ -358,4 +981,100 @@ mod test {
         let x0 = expected_res1();
         assert_eq!(x, x0);
     }
+
+    #[test]
+    fn test_ldlt_solve1() {
+        let mat = test_mat1();
+        let ldlt = LdltSymbolic::new(&mat).factor(&mat);
+        let b = test_vec1();
+        let x = ldlt.solve(&b);
+        assert_eq!(x, expected_res1());
+    }
+
+    #[test]
+    fn test_ldlt_solve_multiple1() {
+        let mat = test_mat1();
+        let ldlt = LdltSymbolic::new(&mat).factor(&mat);
+        let b = test_vec1();
+        // two identical right-hand-sides stored column-major
+        let mut rhs = b.clone();
+        rhs.extend_from_slice(&b);
+        ldlt.solve_multiple(&mut rhs, 2);
+        let expected = expected_res1();
+        assert_eq!(&rhs[..10], &expected[..]);
+        assert_eq!(&rhs[10..], &expected[..]);
+    }
+
+    #[test]
+    fn test_amd_order1() {
+        let mat = test_mat1();
+        let perm = amd_order(&mat);
+
+        // the ordering must be a bijection of 0..n: every index appears
+        // exactly once, so a duplicate or a missing variable is caught
+        let mut seen = vec![false; 10];
+        for i in 0..10 {
+            let p = perm.at(i);
+            assert!(p < 10);
+            assert!(!seen[p]);
+            seen[p] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+
+        // and it must not introduce more fill-in than the identity ordering
+        let amd_nnz = LdltSymbolic::new_perm(&mat, amd_order(&mat)).nnz();
+        let id_nnz = LdltSymbolic::new(&mat).nnz();
+        assert!(amd_nnz <= id_nnz);
+    }
+
+    #[test]
+    fn test_hermitian_solve() {
+        // A = [ 2      1+i ]   (Hermitian, stored fully in CSC)
+        //     [ 1-i    3   ]
+        let i = Complex::new(0., 1.);
+        let one = Complex::new(1., 0.);
+        let two = Complex::new(2., 0.);
+        let three = Complex::new(3., 0.);
+        let indptr = vec![0, 2, 4];
+        let indices = vec![0, 1, 0, 1];
+        let data = vec![two, one - i, one + i, three];
+        let mat = CsMat::from_vecs(CSC, 2, 2, indptr, indices, data).unwrap();
+
+        // solve against b = [1, 1]
+        let b = vec![one, one];
+        let ldlt = LdltSymbolic::new(&mat).factor(&mat);
+        let x = ldlt.solve(&b);
+
+        // det(A) = 6 - (1+i)(1-i) = 4, so the exact solution is
+        // x = [ (2 - i)/4, (1 + i)/4 ]
+        let x0 = vec![Complex::new(0.5, -0.25), Complex::new(0.25, 0.25)];
+        for (xi, x0i) in x.iter().zip(x0.iter()) {
+            assert!((xi - x0i).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_etree1() {
+        let mat = test_mat1();
+        let perm: Permutation<&[usize]> = Permutation::identity();
+        let tree = etree(&mat, &perm);
+
+        // the postorder must list every node exactly once, and a node must
+        // always come before its parent
+        assert_eq!(tree.len(), 10);
+        let mut seen = vec![false; 10];
+        let mut position = vec![0usize; 10];
+        for (pos, &node) in tree.postorder().iter().enumerate() {
+            assert!(!seen[node]);
+            seen[node] = true;
+            position[node] = pos;
+        }
+        assert!(seen.iter().all(|&s| s));
+        for node in 0..10 {
+            let parent = tree.parents()[node];
+            if parent >= 0 {
+                assert!(position[node] < position[parent as usize]);
+            }
+        }
+    }
 }
@@ -0,0 +1,340 @@
+/// Sparse LU factorization of general (non-symmetric) matrices.
+///
+/// This follows the left-looking Gilbert-Peierls approach used by csparse
+/// and Eigen's `SparseLU`: each column `k` is obtained by solving the sparse
+/// lower-triangular system `L x = A(:,k)` against the part of `L` built so
+/// far, using a depth-first traversal to find the nonzero pattern of the
+/// solution before computing its values. The largest-magnitude entry among
+/// the rows not yet pivoted (on or below the diagonal) is taken as the pivot (partial pivoting) and recorded in a
+/// row permutation, and the column is split into the upper factor `U`
+/// (entries at or above the pivot) and the unit lower factor `L` (entries
+/// below, scaled by the pivot).
+
+use std::ops::Deref;
+
+use num::traits::Num;
+
+use sparse::csmat::CsMat;
+
+/// Symbolic information for an LU factorization.
+///
+/// Partial pivoting makes the final nonzero pattern value-dependent, so the
+/// symbolic phase only fixes the problem size (and, in a richer
+/// implementation, a fill-reducing column ordering); the reachable-pattern
+/// analysis proper happens column by column inside `lu_numeric`.
+pub struct LuSymbolic {
+    n: usize,
+}
+
+/// Run the symbolic analysis of `mat`.
+pub fn lu_symbolic<N, IStorage, DStorage>(
+    mat: &CsMat<N, IStorage, DStorage>) -> LuSymbolic
+where
+N: Clone + Copy + PartialEq,
+IStorage: Deref<Target=[usize]>,
+DStorage: Deref<Target=[N]> {
+    let n = mat.rows();
+    assert_eq!(n, mat.cols(), "LU requires a square matrix");
+    LuSymbolic { n: n }
+}
+
+/// A numeric LU factorization `P A = L U`, with `L` unit lower triangular,
+/// `U` upper triangular and `P` the partial-pivoting row permutation.
+pub struct LuNumeric<N> {
+    n: usize,
+    l_colptr: Vec<usize>,
+    l_indices: Vec<usize>,
+    l_data: Vec<N>,
+    u_colptr: Vec<usize>,
+    u_indices: Vec<usize>,
+    u_data: Vec<N>,
+    // row permutation: pinv[original_row] is the position of that row in P
+    pinv: Vec<isize>,
+}
+
+/// Depth-first search from node `j0` over the columns of `L` (stored in
+/// `l_colptr`/`l_indices` with original row indices, interpreted through
+/// `pinv`), pushing the reached nodes into `xi` from `top` downwards.
+fn dfs(
+    j0: usize,
+    l_colptr: &[usize],
+    l_indices: &[usize],
+    pinv: &[isize],
+    top: usize,
+    xi: &mut [usize],
+    pstack: &mut [usize],
+    mark: &mut [isize],
+    k: isize) -> usize {
+    let mut top = top;
+    let mut head: isize = 0;
+    xi[0] = j0;
+    while head >= 0 {
+        let h = head as usize;
+        let j = xi[h];
+        let jnew = pinv[j];
+        if mark[j] != k {
+            mark[j] = k;
+            pstack[h] = if jnew < 0 { 0 } else { l_colptr[jnew as usize] };
+        }
+        let pend = if jnew < 0 { 0 } else { l_colptr[jnew as usize + 1] };
+        let mut done = true;
+        let mut p = pstack[h];
+        while p < pend {
+            let i = l_indices[p];
+            if mark[i] == k {
+                p += 1;
+                continue;
+            }
+            pstack[h] = p + 1; // resume here when we come back to this node
+            head += 1;
+            xi[head as usize] = i;
+            done = false;
+            break;
+        }
+        if done {
+            head -= 1;
+            top -= 1;
+            xi[top] = j;
+        }
+    }
+    top
+}
+
+/// Compute the nonzero pattern of the solution of `L x = A(:,k)` by a DFS
+/// over the reachable columns of `L`. The pattern is returned as
+/// `xi[top..n]`, in topological order.
+fn reach(
+    a_rows: &[usize],
+    l_colptr: &[usize],
+    l_indices: &[usize],
+    pinv: &[isize],
+    xi: &mut [usize],
+    pstack: &mut [usize],
+    mark: &mut [isize],
+    k: isize,
+    n: usize) -> usize {
+    let mut top = n;
+    for &i in a_rows {
+        if mark[i] != k {
+            top = dfs(i, l_colptr, l_indices, pinv, top, xi, pstack, mark, k);
+        }
+    }
+    top
+}
+
+/// Compute the numeric factorization of `mat`.
+pub fn lu_numeric<N, IStorage, DStorage>(
+    symbolic: &LuSymbolic,
+    mat: &CsMat<N, IStorage, DStorage>) -> LuNumeric<N>
+where
+N: Clone + Copy + PartialEq + Num + PartialOrd,
+IStorage: Deref<Target=[usize]>,
+DStorage: Deref<Target=[N]> {
+    let n = symbolic.n;
+    assert_eq!(n, mat.rows());
+    assert_eq!(n, mat.cols());
+
+    let mut l_colptr = vec![0; n + 1];
+    let mut l_indices = Vec::new();
+    let mut l_data = Vec::new();
+    let mut u_colptr = vec![0; n + 1];
+    let mut u_indices = Vec::new();
+    let mut u_data = Vec::new();
+    let mut pinv = vec![-1isize; n];
+
+    let mut x = vec![N::zero(); n];
+    let mut xi = vec![0usize; n];
+    let mut pstack = vec![0usize; n];
+    let mut mark = vec![-1isize; n];
+
+    for (k, (_, col)) in mat.outer_iterator().enumerate() {
+        let a_col: Vec<(usize, N)> = col.iter().collect();
+        let a_rows: Vec<usize> = a_col.iter().map(|&(i, _)| i).collect();
+
+        // symbolic step: nonzero pattern of the triangular solve
+        let top = reach(&a_rows, &l_colptr, &l_indices, &pinv,
+                        &mut xi, &mut pstack, &mut mark, k as isize, n);
+
+        // numeric step: sparse triangular solve L x = A(:,k)
+        for &i in &xi[top..n] {
+            x[i] = N::zero();
+        }
+        for &(i, val) in &a_col {
+            x[i] = val;
+        }
+        for idx in top..n {
+            let i = xi[idx];
+            let j = pinv[i];
+            if j < 0 {
+                continue;
+            }
+            let j = j as usize;
+            let xi_val = x[i];
+            // subtract column j of L, skipping its unit diagonal (stored first)
+            for p in (l_colptr[j] + 1)..l_colptr[j + 1] {
+                let row = l_indices[p];
+                x[row] = x[row] - l_data[p] * xi_val;
+            }
+        }
+
+        // partial pivoting: pick the largest-magnitude entry among the rows
+        // not yet used as a pivot; the others go straight to U
+        let mut ipiv: isize = -1;
+        let mut best = N::zero();
+        for &i in &xi[top..n] {
+            if pinv[i] < 0 {
+                let v = x[i];
+                let mag = if v < N::zero() { N::zero() - v } else { v };
+                if ipiv < 0 || mag > best {
+                    best = mag;
+                    ipiv = i as isize;
+                }
+            } else {
+                u_indices.push(pinv[i] as usize);
+                u_data.push(x[i]);
+            }
+        }
+        assert!(ipiv >= 0, "LU factorization failed: singular matrix");
+        let ipiv = ipiv as usize;
+        let pivot = x[ipiv];
+
+        // diagonal of U and pivot bookkeeping
+        u_indices.push(k);
+        u_data.push(pivot);
+        pinv[ipiv] = k as isize;
+
+        // unit diagonal of L first, then the entries below the pivot, scaled
+        l_indices.push(ipiv);
+        l_data.push(N::one());
+        for &i in &xi[top..n] {
+            if pinv[i] < 0 {
+                l_indices.push(i);
+                l_data.push(x[i] / pivot);
+            }
+            x[i] = N::zero();
+        }
+
+        l_colptr[k + 1] = l_indices.len();
+        u_colptr[k + 1] = u_indices.len();
+    }
+
+    // remap the stored L row indices from their original numbering to the
+    // pivoted one
+    for p in 0..l_indices.len() {
+        l_indices[p] = pinv[l_indices[p]] as usize;
+    }
+
+    LuNumeric {
+        n: n,
+        l_colptr: l_colptr,
+        l_indices: l_indices,
+        l_data: l_data,
+        u_colptr: u_colptr,
+        u_indices: u_indices,
+        u_data: u_data,
+        pinv: pinv,
+    }
+}
+
+impl<N> LuNumeric<N>
+where N: Clone + Copy + PartialEq + Num + PartialOrd {
+
+    /// Factor `mat` in one step, running the symbolic and numeric phases.
+    pub fn new<IStorage, DStorage>(
+        mat: &CsMat<N, IStorage, DStorage>) -> LuNumeric<N>
+    where
+    IStorage: Deref<Target=[usize]>,
+    DStorage: Deref<Target=[N]> {
+        let symbolic = lu_symbolic(mat);
+        lu_numeric(&symbolic, mat)
+    }
+
+    /// The size of the factored system.
+    pub fn problem_size(&self) -> usize {
+        self.n
+    }
+
+    /// Solve the system `A x = b`, by applying the row permutation, then a
+    /// forward solve with `L` and a backward solve with `U`.
+    pub fn solve(&self, b: &[N]) -> Vec<N> {
+        let n = self.n;
+        let mut x = vec![N::zero(); n];
+        for i in 0..n {
+            x[self.pinv[i] as usize] = b[i];
+        }
+        solve_lower_triangular(&self.l_colptr, &self.l_indices, &self.l_data,
+                               &mut x);
+        solve_upper_triangular(&self.u_colptr, &self.u_indices, &self.u_data,
+                               &mut x);
+        x
+    }
+}
+
+/// Forward solve `L x = b` for a unit lower triangular `L` in CSC form,
+/// overwriting `x` (initialized to `b`). The unit diagonal is stored as the
+/// first entry of each column.
+pub fn solve_lower_triangular<N>(
+    l_colptr: &[usize],
+    l_indices: &[usize],
+    l_data: &[N],
+    x: &mut [N])
+where
+N: Clone + Copy + Num {
+    let n = l_colptr.len() - 1;
+    for j in 0..n {
+        for p in (l_colptr[j] + 1)..l_colptr[j + 1] {
+            let row = l_indices[p];
+            x[row] = x[row] - l_data[p] * x[j];
+        }
+    }
+}
+
+/// Backward solve `U x = b` for an upper triangular `U` in CSC form,
+/// overwriting `x` (initialized to `b`). The diagonal is stored as the last
+/// entry of each column.
+pub fn solve_upper_triangular<N>(
+    u_colptr: &[usize],
+    u_indices: &[usize],
+    u_data: &[N],
+    x: &mut [N])
+where
+N: Clone + Copy + Num {
+    let n = u_colptr.len() - 1;
+    for j in (0..n).rev() {
+        let diag_p = u_colptr[j + 1] - 1;
+        x[j] = x[j] / u_data[diag_p];
+        for p in u_colptr[j]..diag_p {
+            let row = u_indices[p];
+            x[row] = x[row] - u_data[p] * x[j];
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sparse::csmat::CsMat;
+    use sparse::csmat::CompressedStorage::CSC;
+    use super::LuNumeric;
+
+    fn nonsym_mat() -> CsMat<f64, Vec<usize>, Vec<f64>> {
+        // [ 4  3  0 ]
+        // [ 6  3  0 ]   (non-symmetric: a[0,1] = 3 != a[1,0] = 6)
+        // [ 0  0  2 ]
+        let indptr = vec![0, 2, 4, 5];
+        let indices = vec![0, 1, 0, 1, 2];
+        let data = vec![4., 6., 3., 3., 2.];
+        CsMat::from_vecs(CSC, 3, 3, indptr, indices, data).unwrap()
+    }
+
+    #[test]
+    fn test_factor_solve1() {
+        let mat = nonsym_mat();
+        let lu = LuNumeric::new(&mat);
+        let b = vec![10., 12., 4.];
+        let x = lu.solve(&b);
+        let x0 = vec![1., 2., 2.];
+        for (xi, x0i) in x.iter().zip(x0.iter()) {
+            assert!((xi - x0i).abs() < 1e-10);
+        }
+    }
+}
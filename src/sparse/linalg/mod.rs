@@ -0,0 +1,4 @@
+//! Sparse linear algebra: factorizations and triangular solves.
+
+pub mod cholesky;
+pub mod lu;